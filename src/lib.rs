@@ -1,75 +1,269 @@
-use std::collections::{BTreeSet, HashMap};
-use std::hash::Hash;
+use std::borrow::Borrow;
+use std::collections::btree_map;
+use std::collections::BTreeMap;
+use std::ops::{Bound, RangeBounds};
 
 #[derive(Clone, Debug)]
-pub struct LatestMap<Key: Eq + Hash + Clone + Ord, Value> {
-    pub(crate) data: HashMap<Key, Value>,
-    data_index: BTreeSet<Key>,
+pub struct LatestMap<Key: Ord, Value> {
+    pub(crate) data: BTreeMap<Key, Value>,
+    capacity_limit: Option<usize>,
 }
 
-impl<Key: Eq + Hash + Clone + Ord, Value> Default for LatestMap<Key, Value> {
+impl<Key: Ord, Value> Default for LatestMap<Key, Value> {
     fn default() -> Self {
         Self {
-            data: HashMap::new(),
-            data_index: BTreeSet::new(),
+            data: BTreeMap::new(),
+            capacity_limit: None,
         }
     }
 }
 
-impl<Key: Eq + Hash + Clone + Ord, Value> LatestMap<Key, Value> {
-    pub fn insert(&mut self, key: Key, value: Value) {
-        self.data.insert(key.clone(), value);
-        self.data_index.insert(key);
-    }
-    pub fn get_latest(&self, key: &Key) -> Option<&Value> {
-        let target_key = if self.data_index.contains(key) {
-            key
-        } else {
-            let sorted_keys: Vec<&Key> = self.data_index.iter().collect();
-            match sorted_keys.binary_search(&key) {
-                Ok(_) => key,
-                Err(gt_index) => {
-                    if gt_index == 0 {
-                        return None;
-                    }
-                    sorted_keys[gt_index - 1]
-                }
+impl<Key: Ord, Value> LatestMap<Key, Value> {
+    /// Creates a bounded map that retains at most `limit` entries. Once full, each
+    /// [`insert`](Self::insert) of a new key evicts the smallest (oldest) key first and
+    /// returns the evicted entry, turning the map into a sliding window over the most
+    /// recent observations. A `limit` of 0 behaves like 1: the most recently inserted
+    /// entry is kept.
+    pub fn with_capacity_limit(limit: usize) -> Self {
+        Self {
+            data: BTreeMap::new(),
+            capacity_limit: Some(limit),
+        }
+    }
+
+    /// Inserts a key/value pair. When the map is bounded (see
+    /// [`with_capacity_limit`](Self::with_capacity_limit)) and inserting a new key would
+    /// grow it beyond the limit, the current oldest entry is evicted *before* the insert
+    /// and returned, so the pair just inserted is never handed straight back.
+    pub fn insert(&mut self, key: Key, value: Value) -> Option<(Key, Value)> {
+        let evicted = match self.capacity_limit {
+            Some(limit) if self.data.len() >= limit && !self.data.contains_key(&key) => {
+                self.pop_oldest()
             }
+            _ => None,
         };
+        self.data.insert(key, value);
+        evicted
+    }
 
-        self.data.get(target_key)
+    /// Removes and returns the entry with the smallest (oldest) key, if any.
+    pub fn pop_oldest(&mut self) -> Option<(Key, Value)> {
+        self.data.pop_first()
+    }
+    pub fn get_latest<Q>(&self, key: &Q) -> Option<&Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.data
+            .range((Bound::Unbounded, Bound::Included(key)))
+            .next_back()
+            .map(|(_, v)| v)
+    }
+    pub fn get_latest_with_key<Q>(&self, key: &Q) -> Option<(&Key, &Value)>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.data
+            .range((Bound::Unbounded, Bound::Included(key)))
+            .next_back()
+    }
+    pub fn get_ceiling<Q>(&self, key: &Q) -> Option<(&Key, &Value)>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.data
+            .range((Bound::Included(key), Bound::Unbounded))
+            .next()
     }
     pub fn get_last_with_key(&self) -> Option<(&Key, &Value)> {
-        let sorted_keys: Vec<&Key> = self.data_index.iter().collect();
-        sorted_keys
-            .last()
-            .and_then(|&key| self.data.get(key).map(|v| (key, v)))
+        self.data.iter().next_back()
+    }
+    pub fn range<R: RangeBounds<Key>>(&self, bounds: R) -> impl Iterator<Item = (&Key, &Value)> {
+        self.data.range(bounds)
     }
 
-    pub fn get_mut(&mut self, key: &Key) -> Option<&mut Value> {
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
         self.data.get_mut(key)
     }
-    pub fn contains_key(&self, key: &Key) -> bool {
-        self.data_index.contains(key)
-    }
-
-    pub fn pop_latest(&mut self, key: &Key) -> Option<(Key, Value)> {
-        let target_key = if self.data_index.contains(key) {
-            key.clone()
-        } else {
-            let sorted_keys: Vec<&Key> = self.data_index.iter().collect();
-            match sorted_keys.binary_search(&key) {
-                Ok(_) => key.clone(),
-                Err(gt_index) => {
-                    if gt_index == 0 {
-                        return None;
-                    }
-                    sorted_keys[gt_index - 1].clone()
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.data.contains_key(key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Key, &Value)> {
+        self.data.iter()
+    }
+    pub fn keys(&self) -> impl Iterator<Item = &Key> {
+        self.data.keys()
+    }
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.data.values()
+    }
+
+    /// Returns a view into the entry for `key` for in-place insert-or-update.
+    ///
+    /// Note: unlike [`insert`](Self::insert), the entry API does **not** enforce the
+    /// capacity limit set by [`with_capacity_limit`](Self::with_capacity_limit) — an
+    /// `or_insert*` through a vacant entry can grow the map past its bound. Use
+    /// [`insert`](Self::insert) when the sliding-window guarantee matters, or call
+    /// [`pop_oldest`](Self::pop_oldest) afterwards to trim.
+    pub fn entry(&mut self, key: Key) -> Entry<'_, Key, Value> {
+        match self.data.entry(key) {
+            btree_map::Entry::Occupied(inner) => Entry::Occupied(OccupiedEntry { inner }),
+            btree_map::Entry::Vacant(inner) => Entry::Vacant(VacantEntry { inner }),
+        }
+    }
+
+    pub fn pop_latest<Q>(&mut self, key: &Q) -> Option<(Key, Value)>
+    where
+        Key: Borrow<Q> + Clone,
+        Q: Ord + ?Sized,
+    {
+        let target_key = self
+            .data
+            .range((Bound::Unbounded, Bound::Included(key)))
+            .next_back()
+            .map(|(k, _)| k.clone())?;
+        self.data.remove_entry(target_key.borrow())
+    }
+}
+
+impl<Key: Ord, Value> IntoIterator for LatestMap<Key, Value> {
+    type Item = (Key, Value);
+    type IntoIter = btree_map::IntoIter<Key, Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.into_iter()
+    }
+}
+
+impl<'a, Key: Ord, Value> IntoIterator for &'a LatestMap<Key, Value> {
+    type Item = (&'a Key, &'a Value);
+    type IntoIter = btree_map::Iter<'a, Key, Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.iter()
+    }
+}
+
+impl<Key: Ord, Value> FromIterator<(Key, Value)> for LatestMap<Key, Value> {
+    fn from_iter<T: IntoIterator<Item = (Key, Value)>>(iter: T) -> Self {
+        Self {
+            data: BTreeMap::from_iter(iter),
+            capacity_limit: None,
+        }
+    }
+}
+
+impl<Key: Ord, Value> Extend<(Key, Value)> for LatestMap<Key, Value> {
+    fn extend<T: IntoIterator<Item = (Key, Value)>>(&mut self, iter: T) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<Key: Ord + serde::Serialize, Value: serde::Serialize> serde::Serialize
+    for LatestMap<Key, Value>
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeSeq;
+        let mut seq = serializer.serialize_seq(Some(self.data.len()))?;
+        for entry in &self.data {
+            seq.serialize_element(&entry)?;
+        }
+        seq.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, Key, Value> serde::Deserialize<'de> for LatestMap<Key, Value>
+where
+    Key: Ord + serde::Deserialize<'de>,
+    Value: serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SeqVisitor<Key, Value>(std::marker::PhantomData<(Key, Value)>);
+
+        impl<'de, Key, Value> serde::de::Visitor<'de> for SeqVisitor<Key, Value>
+        where
+            Key: Ord + serde::Deserialize<'de>,
+            Value: serde::Deserialize<'de>,
+        {
+            type Value = LatestMap<Key, Value>;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a sequence of key/value pairs")
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<Self::Value, A::Error> {
+                let mut data = BTreeMap::new();
+                while let Some((key, value)) = seq.next_element()? {
+                    data.insert(key, value);
                 }
+                Ok(LatestMap {
+                    data,
+                    capacity_limit: None,
+                })
             }
-        };
-        self.data_index.remove(&target_key);
-        self.data.remove(&target_key).map(|value|(target_key, value))
+        }
+
+        deserializer.deserialize_seq(SeqVisitor(std::marker::PhantomData))
+    }
+}
+
+/// A view into a single entry of a [`LatestMap`], which may be either occupied or vacant.
+///
+/// Constructed from [`LatestMap::entry`].
+pub enum Entry<'a, Key: Ord, Value> {
+    Occupied(OccupiedEntry<'a, Key, Value>),
+    Vacant(VacantEntry<'a, Key, Value>),
+}
+
+/// A view into an occupied entry. Part of the [`Entry`] enum.
+pub struct OccupiedEntry<'a, Key: Ord, Value> {
+    inner: btree_map::OccupiedEntry<'a, Key, Value>,
+}
+
+/// A view into a vacant entry. Part of the [`Entry`] enum.
+pub struct VacantEntry<'a, Key: Ord, Value> {
+    inner: btree_map::VacantEntry<'a, Key, Value>,
+}
+
+impl<'a, Key: Ord, Value> Entry<'a, Key, Value> {
+    pub fn or_insert(self, default: Value) -> &'a mut Value {
+        match self {
+            Entry::Occupied(entry) => entry.inner.into_mut(),
+            Entry::Vacant(entry) => entry.inner.insert(default),
+        }
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> Value>(self, default: F) -> &'a mut Value {
+        match self {
+            Entry::Occupied(entry) => entry.inner.into_mut(),
+            Entry::Vacant(entry) => entry.inner.insert(default()),
+        }
+    }
+
+    pub fn and_modify<F: FnOnce(&mut Value)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.inner.get_mut());
+        }
+        self
     }
 }
 
@@ -81,7 +275,7 @@ mod test {
     fn should_insert() {
         let mut map: LatestMap<i32, i32> = LatestMap::default();
         map.insert(1, 2);
-        assert!(map.data_index.contains(&1));
+        assert!(map.data.contains_key(&1));
         assert_eq!(map.data.get(&1), Some(&2));
     }
 
@@ -119,6 +313,132 @@ mod test {
         assert_eq!(map.get_latest(&1000), Some(&100));
     }
 
+    #[test]
+    fn should_get_latest_with_key() {
+        let mut map: LatestMap<i32, i32> = LatestMap::default();
+        map.insert(1, 2);
+        map.insert(20, 40);
+
+        assert_eq!(map.get_latest_with_key(&0), None);
+        assert_eq!(map.get_latest_with_key(&1), Some((&1, &2)));
+        assert_eq!(map.get_latest_with_key(&24), Some((&20, &40)));
+    }
+
+    #[test]
+    fn should_get_ceiling() {
+        let mut map: LatestMap<i32, i32> = LatestMap::default();
+        map.insert(10, 20);
+        map.insert(20, 40);
+
+        assert_eq!(map.get_ceiling(&0), Some((&10, &20)));
+        assert_eq!(map.get_ceiling(&10), Some((&10, &20)));
+        assert_eq!(map.get_ceiling(&11), Some((&20, &40)));
+        assert_eq!(map.get_ceiling(&21), None);
+    }
+
+    #[test]
+    fn should_iterate_range() {
+        let mut map: LatestMap<i32, i32> = LatestMap::default();
+        map.insert(1, 2);
+        map.insert(10, 20);
+        map.insert(20, 40);
+        map.insert(50, 100);
+
+        let window: Vec<(&i32, &i32)> = map.range(10..50).collect();
+        assert_eq!(window, vec![(&10, &20), (&20, &40)]);
+    }
+
+    #[test]
+    fn should_insert_via_vacant_entry() {
+        let mut map: LatestMap<i32, Vec<i32>> = LatestMap::default();
+        map.entry(1).or_insert_with(Vec::new).push(10);
+        map.entry(1).or_insert_with(Vec::new).push(20);
+        assert_eq!(map.data.get(&1), Some(&vec![10, 20]));
+    }
+
+    #[test]
+    fn should_and_modify_existing_entry() {
+        let mut map: LatestMap<i32, i32> = LatestMap::default();
+        map.insert(1, 2);
+        map.entry(1).and_modify(|v| *v += 10).or_insert(0);
+        map.entry(5).and_modify(|v| *v += 10).or_insert(100);
+        assert_eq!(map.data.get(&1), Some(&12));
+        assert_eq!(map.data.get(&5), Some(&100));
+    }
+
+    #[test]
+    fn should_query_with_borrowed_key() {
+        let mut map: LatestMap<String, i32> = LatestMap::default();
+        map.insert("a".to_string(), 1);
+        map.insert("m".to_string(), 2);
+
+        assert_eq!(map.get_latest("z"), Some(&2));
+        assert_eq!(map.get_latest("b"), Some(&1));
+        assert!(map.contains_key("a"));
+        assert_eq!(map.get_mut("m"), Some(&mut 2));
+        assert_eq!(map.pop_latest("z"), Some(("m".to_string(), 2)));
+    }
+
+    #[test]
+    fn should_build_from_iter_and_iterate_in_order() {
+        let map: LatestMap<i32, i32> = [(20, 40), (1, 2), (10, 20)].into_iter().collect();
+        let pairs: Vec<(i32, i32)> = map.iter().map(|(&k, &v)| (k, v)).collect();
+        assert_eq!(pairs, vec![(1, 2), (10, 20), (20, 40)]);
+        assert_eq!(map.keys().copied().collect::<Vec<_>>(), vec![1, 10, 20]);
+        assert_eq!(map.values().copied().collect::<Vec<_>>(), vec![2, 20, 40]);
+    }
+
+    #[test]
+    fn should_extend_and_consume() {
+        let mut map: LatestMap<i32, i32> = LatestMap::default();
+        map.extend([(1, 2), (10, 20)]);
+        let owned: Vec<(i32, i32)> = map.into_iter().collect();
+        assert_eq!(owned, vec![(1, 2), (10, 20)]);
+    }
+
+    #[test]
+    fn should_evict_oldest_when_over_capacity() {
+        let mut map: LatestMap<i32, i32> = LatestMap::with_capacity_limit(2);
+        assert_eq!(map.insert(1, 10), None);
+        assert_eq!(map.insert(2, 20), None);
+        assert_eq!(map.insert(3, 30), Some((1, 10)));
+        assert_eq!(map.data.len(), 2);
+        assert_eq!(map.get_latest(&1), None);
+        assert_eq!(map.get_latest(&3), Some(&30));
+    }
+
+    #[test]
+    fn should_not_self_evict_when_inserting_new_key_at_capacity() {
+        let mut map: LatestMap<i32, i32> = LatestMap::with_capacity_limit(2);
+        map.insert(2, 20);
+        map.insert(3, 30);
+        // Inserting an older key while full evicts the current oldest, not the new pair.
+        assert_eq!(map.insert(1, 10), Some((2, 20)));
+        assert_eq!(map.data.len(), 2);
+        assert_eq!(map.get_latest(&1), Some(&10));
+        assert_eq!(map.get_latest(&3), Some(&30));
+    }
+
+    #[test]
+    fn should_not_evict_when_overwriting_existing_key_at_capacity() {
+        let mut map: LatestMap<i32, i32> = LatestMap::with_capacity_limit(2);
+        map.insert(1, 10);
+        map.insert(2, 20);
+        assert_eq!(map.insert(2, 99), None);
+        assert_eq!(map.data.len(), 2);
+        assert_eq!(map.get_latest(&2), Some(&99));
+    }
+
+    #[test]
+    fn should_pop_oldest() {
+        let mut map: LatestMap<i32, i32> = LatestMap::default();
+        map.insert(10, 20);
+        map.insert(1, 2);
+        assert_eq!(map.pop_oldest(), Some((1, 2)));
+        assert_eq!(map.pop_oldest(), Some((10, 20)));
+        assert_eq!(map.pop_oldest(), None);
+    }
+
     #[test]
     fn should_work_given_map_is_empty() {
         let map: LatestMap<i32, i32> = LatestMap::default();
@@ -127,6 +447,23 @@ mod test {
         assert_eq!(map.get_latest(&3), None);
     }
 
+    #[cfg(feature = "serde")]
+    #[test]
+    fn should_round_trip_through_serde_as_sequence() {
+        let mut map: LatestMap<i32, i32> = LatestMap::default();
+        map.insert(20, 40);
+        map.insert(1, 2);
+        map.insert(10, 20);
+
+        let json = serde_json::to_string(&map).unwrap();
+        // Encoded as an ordered sequence of pairs, not a JSON object.
+        assert_eq!(json, "[[1,2],[10,20],[20,40]]");
+
+        let restored: LatestMap<i32, i32> = serde_json::from_str(&json).unwrap();
+        let pairs: Vec<(i32, i32)> = restored.iter().map(|(&k, &v)| (k, v)).collect();
+        assert_eq!(pairs, vec![(1, 2), (10, 20), (20, 40)]);
+    }
+
     #[test]
     fn should_work_pop_latest() {
         let mut map: LatestMap<i32, i32> = LatestMap::default();
@@ -139,8 +476,7 @@ mod test {
         assert_eq!(map.data.len(), 4);
         assert_eq!(map.get_latest(&1), Some(&2));
         assert_eq!(map.data.len(), 4);
-        assert_eq!(map.pop_latest(&3), Some((1,2)));
+        assert_eq!(map.pop_latest(&3), Some((1, 2)));
         assert_eq!(map.data.len(), 3);
-        assert_eq!(map.data_index.len(), 3);
     }
 }